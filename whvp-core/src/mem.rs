@@ -1,10 +1,13 @@
+use std::cell::RefCell;
 use std::error::Error;
 use std::fmt;
 use std::iter;
 use std::mem;
 
 use std::collections::HashMap;
+use std::collections::HashSet;
 use std::hash::BuildHasherDefault;
+use std::sync::Arc;
 
 use fnv::FnvHasher;
 
@@ -35,6 +38,22 @@ const fn pt_index(gva: Gva) -> u64 {
     gva >> (12 + (9 * 0)) & 0x1ff
 }
 
+const fn pml5_index(gva: Gva) -> u64 {
+    gva >> (12 + (9 * 4)) & 0x1ff
+}
+
+/// Depth of the page-table hierarchy rooted at `cr3`. Only `Long5Level`
+/// currently changes how `translate_gva` walks (it adds the extra PML5
+/// level that LA57 guests use); `Legacy32` and `Pae` are carried for
+/// completeness but still fall through to the existing 4-level logic.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum PagingMode {
+    Legacy32,
+    Pae,
+    Long4Level,
+    Long5Level,
+}
+
 const fn base_flags(gpa: Gpa) -> (Gpa, u64) {
     (gpa & !0xfff & 0x000f_ffff_ffff_ffff, gpa & 0x1ff)
 }
@@ -47,6 +66,75 @@ const fn page_offset(gva: Gva) -> u64 {
     gva & 0xfff
 }
 
+/// Effective access permissions for a translated page, derived the way the
+/// x86 MMU derives them: `present`/`writable`/`user` are AND-combined across
+/// every level of the walk (any level revoking the permission revokes it for
+/// the whole translation), `nx` is OR-combined (any level marking the
+/// mapping non-executable makes it non-executable), and `accessed`/`dirty`
+/// reflect only the final entry that was actually resolved.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Default)]
+pub struct PageFlags {
+    pub present: bool,
+    pub writable: bool,
+    pub user: bool,
+    pub nx: bool,
+    pub accessed: bool,
+    pub dirty: bool,
+}
+
+impl PageFlags {
+    /// Identity element for `combine`: AND-combined bits start `true` so the
+    /// first real entry folded in determines them, `nx` starts `false` since
+    /// it is OR-combined.
+    const fn default_effective() -> Self {
+        PageFlags {
+            present: true,
+            writable: true,
+            user: true,
+            nx: false,
+            accessed: false,
+            dirty: false,
+        }
+    }
+
+    const fn from_entry(entry: u64) -> Self {
+        PageFlags {
+            present: entry & 1 != 0,
+            writable: entry & (1 << 1) != 0,
+            user: entry & (1 << 2) != 0,
+            nx: entry & (1 << 63) != 0,
+            accessed: false,
+            dirty: false,
+        }
+    }
+
+    const fn combine(self, other: PageFlags) -> Self {
+        PageFlags {
+            present: self.present && other.present,
+            writable: self.writable && other.writable,
+            user: self.user && other.user,
+            nx: self.nx || other.nx,
+            accessed: false,
+            dirty: false,
+        }
+    }
+
+    const fn with_final_entry(mut self, entry: u64) -> Self {
+        self.accessed = entry & (1 << 5) != 0;
+        self.dirty = entry & (1 << 6) != 0;
+        self
+    }
+}
+
+/// Result of `translate_gva_ext`: the resolved physical address together
+/// with the page size and effective permissions used to reach it.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Translation {
+    pub gpa: Gpa,
+    pub page_size: PageSize,
+    pub flags: PageFlags,
+}
+
 pub trait X64VirtualAddressSpace {
     fn read_gpa(&self, gpa: Gpa, buf: &mut [u8]) -> Result<()>;
 
@@ -106,8 +194,28 @@ pub trait X64VirtualAddressSpace {
         Ok(())
     }
 
+    /// Paging depth to assume when walking `cr3` in `translate_gva`.
+    /// Defaults to the classic 4-level hierarchy; override to `Long5Level`
+    /// for guests that run with LA57 enabled.
+    fn paging_mode(&self) -> PagingMode {
+        PagingMode::Long4Level
+    }
+
     fn translate_gva(&self, cr3: Gpa, gva: Gva) -> Result<Gpa> {
-        let (pml4_base, _) = base_flags(cr3);
+        let (mut pml4_base, _) = base_flags(cr3);
+
+        if self.paging_mode() == PagingMode::Long5Level {
+            let pml5e_addr = pml4_base + pml5_index(gva) * 8;
+            let pml5e = self.read_gpa_u64(pml5e_addr)?;
+
+            let (next_base, pml5e_flags) = base_flags(pml5e);
+
+            if pml5e_flags & 1 == 0 {
+                return Err(anyhow!(VirtMemError::Pml5eNotPresent));
+            }
+
+            pml4_base = next_base;
+        }
 
         let pml4e_addr = pml4_base + pml4_index(gva) * 8;
         let pml4e = self.read_gpa_u64(pml4e_addr)?;
@@ -165,6 +273,208 @@ pub trait X64VirtualAddressSpace {
 
         Ok(pte_paddr + page_offset(gva))
     }
+
+    /// Like `translate_gva`, but also returns the effective `PageFlags` so
+    /// callers can tell whether a GVA is writable, user-accessible, or NX
+    /// before touching it.
+    fn translate_gva_ext(&self, cr3: Gpa, gva: Gva) -> Result<Translation> {
+        let (mut pml4_base, _) = base_flags(cr3);
+
+        let mut flags = PageFlags::default_effective();
+
+        if self.paging_mode() == PagingMode::Long5Level {
+            let pml5e_addr = pml4_base + pml5_index(gva) * 8;
+            let pml5e = self.read_gpa_u64(pml5e_addr)?;
+
+            let (next_base, pml5e_flags) = base_flags(pml5e);
+
+            if pml5e_flags & 1 == 0 {
+                return Err(anyhow!(VirtMemError::Pml5eNotPresent));
+            }
+
+            flags = flags.combine(PageFlags::from_entry(pml5e));
+            pml4_base = next_base;
+        }
+
+        let pml4e_addr = pml4_base + pml4_index(gva) * 8;
+        let pml4e = self.read_gpa_u64(pml4e_addr)?;
+
+        let (pdpt_base, pml4e_flags) = base_flags(pml4e);
+
+        if pml4e_flags & 1 == 0 {
+            return Err(anyhow!(VirtMemError::Pml4eNotPresent));
+        }
+
+        let flags = flags.combine(PageFlags::from_entry(pml4e));
+
+        let pdpte_addr = pdpt_base + pdpt_index(gva) * 8;
+        let pdpte = self.read_gpa_u64(pdpte_addr)?;
+
+        let (pd_base, pdpte_flags) = base_flags(pdpte);
+
+        if pdpte_flags & 1 == 0 {
+            return Err(anyhow!(VirtMemError::PdpteNotPresent));
+        }
+
+        let flags = flags.combine(PageFlags::from_entry(pdpte));
+
+        if pdpte_flags & (1 << 7) != 0 {
+            return Ok(Translation {
+                gpa: pd_base + (gva & PageSize::Size1Gb.mask()),
+                page_size: PageSize::Size1Gb,
+                flags: flags.with_final_entry(pdpte),
+            });
+        }
+
+        let pde_addr = pd_base + pd_index(gva) * 8;
+        let pde = self.read_gpa_u64(pde_addr)?;
+
+        let (pt_base, pde_flags) = base_flags(pde);
+
+        if pde_flags & 1 == 0 {
+            return Err(anyhow!(VirtMemError::PdeNotPresent));
+        }
+
+        let flags = flags.combine(PageFlags::from_entry(pde));
+
+        if pde_flags & (1 << 7) != 0 {
+            return Ok(Translation {
+                gpa: pt_base + (gva & PageSize::Size2Mb.mask()),
+                page_size: PageSize::Size2Mb,
+                flags: flags.with_final_entry(pde),
+            });
+        }
+
+        let pte_addr = pt_base + pt_index(gva) * 8;
+        let pte = self.read_gpa_u64(pte_addr)?;
+
+        let (pte_paddr, pte_raw_flags) = pte_flags(pte);
+
+        if pte_raw_flags & 1 == 0 {
+            return Err(anyhow!(VirtMemError::PteNotPresent));
+        }
+
+        let flags = flags.combine(PageFlags::from_entry(pte));
+
+        Ok(Translation {
+            gpa: pte_paddr + page_offset(gva),
+            page_size: PageSize::Size4Kb,
+            flags: flags.with_final_entry(pte),
+        })
+    }
+
+    /// Like `write_gva`, but errors with `VirtMemError::WriteProtected`
+    /// instead of silently writing through a read-only mapping, and marks
+    /// Accessed on every touched page-table entry and Dirty on the final one
+    /// the way the CPU itself would after an emulated write.
+    fn write_gva_checked(&mut self, cr3: Gpa, gva: Gva, buf: &[u8]) -> Result<()> {
+        let mut off = 0;
+
+        for (start, sz) in chunked(gva, buf.len()) {
+            let translation = self.translate_gva_ext(cr3, start)?;
+
+            if !translation.flags.writable {
+                return Err(anyhow!(VirtMemError::WriteProtected));
+            }
+
+            self.mark_accessed_dirty(cr3, start)?;
+            self.write_gpa(translation.gpa, &buf[off..off + sz])?;
+            off += sz;
+        }
+
+        Ok(())
+    }
+
+    /// Walks `cr3` for `gva` again, setting the Accessed bit on every
+    /// intermediate entry and Accessed+Dirty on the final entry actually
+    /// resolved (huge-page PDPTE/PDE act as their own final entry).
+    fn mark_accessed_dirty(&mut self, cr3: Gpa, gva: Gva) -> Result<()> {
+        let (mut pml4_base, _) = base_flags(cr3);
+
+        if self.paging_mode() == PagingMode::Long5Level {
+            let pml5e_addr = pml4_base + pml5_index(gva) * 8;
+            let pml5e = self.read_gpa_u64(pml5e_addr)?;
+
+            let (next_base, pml5e_flags) = base_flags(pml5e);
+
+            if pml5e_flags & 1 == 0 {
+                return Err(anyhow!(VirtMemError::Pml5eNotPresent));
+            }
+
+            self.mark_accessed(pml5e_addr, pml5e)?;
+            pml4_base = next_base;
+        }
+
+        let pml4e_addr = pml4_base + pml4_index(gva) * 8;
+        let pml4e = self.read_gpa_u64(pml4e_addr)?;
+
+        let (pdpt_base, pml4e_flags) = base_flags(pml4e);
+
+        if pml4e_flags & 1 == 0 {
+            return Err(anyhow!(VirtMemError::Pml4eNotPresent));
+        }
+
+        self.mark_accessed(pml4e_addr, pml4e)?;
+
+        let pdpte_addr = pdpt_base + pdpt_index(gva) * 8;
+        let pdpte = self.read_gpa_u64(pdpte_addr)?;
+
+        let (pd_base, pdpte_flags) = base_flags(pdpte);
+
+        if pdpte_flags & 1 == 0 {
+            return Err(anyhow!(VirtMemError::PdpteNotPresent));
+        }
+
+        if pdpte_flags & (1 << 7) != 0 {
+            return self.mark_accessed_and_dirty(pdpte_addr, pdpte);
+        }
+
+        self.mark_accessed(pdpte_addr, pdpte)?;
+
+        let pde_addr = pd_base + pd_index(gva) * 8;
+        let pde = self.read_gpa_u64(pde_addr)?;
+
+        let (pt_base, pde_flags) = base_flags(pde);
+
+        if pde_flags & 1 == 0 {
+            return Err(anyhow!(VirtMemError::PdeNotPresent));
+        }
+
+        if pde_flags & (1 << 7) != 0 {
+            return self.mark_accessed_and_dirty(pde_addr, pde);
+        }
+
+        self.mark_accessed(pde_addr, pde)?;
+
+        let pte_addr = pt_base + pt_index(gva) * 8;
+        let pte = self.read_gpa_u64(pte_addr)?;
+
+        let (_, pte_raw_flags) = pte_flags(pte);
+
+        if pte_raw_flags & 1 == 0 {
+            return Err(anyhow!(VirtMemError::PteNotPresent));
+        }
+
+        self.mark_accessed_and_dirty(pte_addr, pte)
+    }
+
+    fn mark_accessed(&mut self, addr: Gpa, entry: u64) -> Result<()> {
+        if entry & (1 << 5) == 0 {
+            self.write_gpa(addr, &(entry | (1 << 5)).to_le_bytes())?;
+        }
+
+        Ok(())
+    }
+
+    fn mark_accessed_and_dirty(&mut self, addr: Gpa, entry: u64) -> Result<()> {
+        let updated = entry | (1 << 5) | (1 << 6);
+
+        if updated != entry {
+            self.write_gpa(addr, &updated.to_le_bytes())?;
+        }
+
+        Ok(())
+    }
 }
 
 pub struct Allocator {
@@ -247,14 +557,751 @@ impl X64VirtualAddressSpace for GpaManager {
     }
 }
 
+#[test]
+fn test_translate_gva_ext_readonly_intermediate_entry_is_not_writable() {
+    const PRESENT: u64 = 1;
+    const WRITABLE: u64 = 1 << 1;
+
+    let mut gm = GpaManager::new();
+
+    // gva = 0 walks index 0 at every level, so a single chain of tables
+    // suffices: PML4 -> PDPT -> PD -> PT -> data, all writable except the PD
+    // entry, which is present but read-only.
+    for base in [0x1000u64, 0x2000, 0x3000, 0x4000, 0x5000] {
+        gm.add_page(base, [0; 4096]);
+    }
+
+    gm.write_gpa(0x1000, &(0x2000u64 | PRESENT | WRITABLE).to_le_bytes())
+        .unwrap();
+    gm.write_gpa(0x2000, &(0x3000u64 | PRESENT | WRITABLE).to_le_bytes())
+        .unwrap();
+    gm.write_gpa(0x3000, &(0x4000u64 | PRESENT).to_le_bytes())
+        .unwrap();
+    gm.write_gpa(0x4000, &(0x5000u64 | PRESENT | WRITABLE).to_le_bytes())
+        .unwrap();
+
+    let translation = gm.translate_gva_ext(0x1000, 0x0).unwrap();
+
+    assert!(translation.flags.present);
+    assert!(!translation.flags.writable);
+}
+
+/// Copy-on-write overlay on top of a shared `GpaManager` snapshot, for fast
+/// rewind between fuzz iterations. Reads fall through to `base` until a page
+/// is written, at which point it is cloned into `overlay` and mutated there;
+/// `reset` then drops just the overlay to restore the pristine snapshot in
+/// time proportional to pages touched, not total guest memory.
+pub struct CowGpaManager {
+    base: Arc<GpaManager>,
+    overlay: FastMap64<u64, [u8; 4096]>,
+    dirty: HashSet<u64>,
+}
+
+impl CowGpaManager {
+    pub fn new(base: Arc<GpaManager>) -> Self {
+        CowGpaManager {
+            base,
+            overlay: FastMap64::default(),
+            dirty: HashSet::new(),
+        }
+    }
+
+    /// Drops the overlay and dirty set, restoring the pristine base snapshot.
+    pub fn reset(&mut self) {
+        self.overlay.clear();
+        self.dirty.clear();
+    }
+
+    /// Bases of the pages mutated since the last `reset`, for diffing or
+    /// serializing just the dirtied frames instead of the whole snapshot.
+    pub fn dirty_pages(&self) -> impl Iterator<Item = Gpa> + '_ {
+        self.dirty.iter().copied()
+    }
+}
+
+impl X64VirtualAddressSpace for CowGpaManager {
+    fn read_gpa(&self, gpa: Gpa, buf: &mut [u8]) -> Result<()> {
+        if gpa + (buf.len() as Gpa) > (gpa & !0xfff) + 0x1000 {
+            return Err(anyhow!(VirtMemError::SpanningPage));
+        }
+
+        let (base, off) = page_off(gpa);
+
+        if let Some(page) = self.overlay.get(&base) {
+            return Ok(buf.copy_from_slice(&page[off..off + buf.len()]));
+        }
+
+        self.base.read_gpa(gpa, buf)
+    }
+
+    fn write_gpa(&mut self, gpa: Gpa, data: &[u8]) -> Result<()> {
+        if gpa + (data.len() as Gpa) > (gpa & !0xfff) + 0x1000 {
+            return Err(anyhow!(VirtMemError::SpanningPage));
+        }
+
+        let (base, off) = page_off(gpa);
+
+        if !self.overlay.contains_key(&base) {
+            let mut page = [0; 4096];
+            self.base.read_gpa(base, &mut page)?;
+            self.overlay.insert(base, page);
+        }
+
+        let page = self.overlay.get_mut(&base).unwrap();
+        page[off..off + data.len()].copy_from_slice(data);
+        self.dirty.insert(base);
+
+        Ok(())
+    }
+}
+
+#[test]
+fn test_cow_gpa_manager_reset_restores_pristine_snapshot() {
+    let mut base = GpaManager::new();
+    base.add_page(0x1000, [0xaa; 4096]);
+
+    let mut cow = CowGpaManager::new(Arc::new(base));
+
+    cow.write_gpa(0x1000, &[0xbb; 4]).unwrap();
+
+    let mut buf = [0; 4];
+    cow.read_gpa(0x1000, &mut buf).unwrap();
+    assert_eq!(buf, [0xbb; 4]);
+    assert_eq!(cow.dirty_pages().collect::<Vec<_>>(), vec![0x1000]);
+
+    cow.reset();
+
+    let mut buf = [0; 4];
+    cow.read_gpa(0x1000, &mut buf).unwrap();
+    assert_eq!(buf, [0xaa; 4]);
+    assert_eq!(cow.dirty_pages().count(), 0);
+}
+
+/// Handle to an immutable snapshot of an `MmapGpaManager`'s gpa->offset
+/// index, returned by `commit` and consumed by `open_version`. Tagged with
+/// the id of the manager that produced it so a `Version` from one manager
+/// can't silently be replayed against another.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub struct Version {
+    owner: u64,
+    idx: usize,
+}
+
+/// `X64VirtualAddressSpace` backend for guest snapshots too large to keep
+/// fully resident: frames live in a memory-mapped file and only a
+/// `gpa -> file-offset` index (`FastMap64<u64, usize>`) is kept in RAM,
+/// demand-loading slices on `read_gpa`. A page still shared with a committed
+/// version is copy-on-write: its first write since `commit`/`open_version`
+/// appends a fresh frame (tracked in `dirty`), so `commit` can snapshot the
+/// index into an immutable root in O(pages mapped) and `open_version` can
+/// fork a new lineage from any prior root while still sharing every frame
+/// neither lineage has diverged on — the copy-on-write B-tree idea from
+/// sanakirja, applied to flat 4 KB guest frames instead of B-tree nodes.
+///
+/// The file is append-only: nothing currently reclaims a frame once no live
+/// `Version` can still reach it (e.g. after repeated `open_version` rollback
+/// to the same root), so the backing file grows monotonically across
+/// fuzz-iteration resets rather than staying bounded by live guest memory.
+/// Compaction (walking `versions` for still-referenced offsets and rewriting
+/// the file without the rest) would fix this but isn't implemented — for
+/// long-running fuzzing, recreate the manager periodically instead of
+/// relying on `open_version` to bound disk usage.
+///
+/// Depends on the `memmap2` crate for `MmapMut`; add it to `whvp-core`'s
+/// manifest alongside `fnv`/`anyhow` if it isn't already there.
+pub struct MmapGpaManager {
+    id: u64,
+    file: std::fs::File,
+    mmap: memmap2::MmapMut,
+    index: FastMap64<u64, usize>,
+    next_offset: usize,
+    versions: Vec<FastMap64<u64, usize>>,
+    /// Bases already appended as a fresh frame since the last `commit` (or
+    /// `open_version`), and so safe to overwrite in place: nothing else can
+    /// be pointing at that frame's offset.
+    dirty: HashSet<u64>,
+}
+
+impl MmapGpaManager {
+    const GROWTH: usize = 64 * 1024 * 1024;
+
+    /// Creates a brand-new, empty snapshot store backed by `path`, truncating
+    /// any existing file there first. `index`/`next_offset` always start
+    /// empty, so reopening a file written by a previous process as if it
+    /// were a persisted snapshot is not supported: use a fresh path per run,
+    /// or delete the old file first. Don't call this twice on the same path
+    /// while an earlier `MmapGpaManager` for it is still alive — the second
+    /// `create` truncates the file out from under the first.
+    pub fn create(path: &std::path::Path) -> Result<Self> {
+        static NEXT_ID: std::sync::atomic::AtomicU64 = std::sync::atomic::AtomicU64::new(0);
+
+        let file = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)?;
+
+        file.set_len(Self::GROWTH as u64)?;
+
+        let mmap = unsafe { memmap2::MmapMut::map_mut(&file)? };
+
+        Ok(MmapGpaManager {
+            id: NEXT_ID.fetch_add(1, std::sync::atomic::Ordering::Relaxed),
+            file,
+            mmap,
+            index: FastMap64::default(),
+            next_offset: 0,
+            versions: Vec::new(),
+            dirty: HashSet::new(),
+        })
+    }
+
+    fn grow_if_needed(&mut self, required: usize) -> Result<()> {
+        if required <= self.mmap.len() {
+            return Ok(());
+        }
+
+        let new_len = required.div_ceil(Self::GROWTH) * Self::GROWTH;
+        self.file.set_len(new_len as u64)?;
+        self.mmap = unsafe { memmap2::MmapMut::map_mut(&self.file)? };
+
+        Ok(())
+    }
+
+    pub fn add_page(&mut self, gpa: Gpa, page: &[u8; 4096]) -> Result<()> {
+        let (base, _) = page_off(gpa);
+        let offset = self.next_offset;
+
+        self.grow_if_needed(offset + 4096)?;
+        self.mmap[offset..offset + 4096].copy_from_slice(page);
+
+        self.index.insert(base, offset);
+        self.next_offset += 4096;
+
+        Ok(())
+    }
+
+    /// Snapshots the current gpa->offset index into an immutable root and
+    /// returns a handle to it. Existing frames are never mutated, so every
+    /// version sharing them stays valid. Every currently owned frame becomes
+    /// shared with this root, so the next write to any of them must
+    /// copy-on-write a fresh frame again.
+    pub fn commit(&mut self) -> Version {
+        self.versions.push(self.index.clone());
+        self.dirty.clear();
+
+        Version {
+            owner: self.id,
+            idx: self.versions.len() - 1,
+        }
+    }
+
+    /// Forks the active index from a prior `commit` on this same manager, so
+    /// this lineage shares every frame the new lineage hasn't diverged on
+    /// yet. Frames written since that `commit` and now unreachable (the old
+    /// `index` this call discards was the only reference to them) are not
+    /// reclaimed — see the struct docs. Rolling back the same version
+    /// repeatedly across many fuzz iterations therefore keeps growing the
+    /// file rather than reusing that dead space.
+    pub fn open_version(&mut self, version: Version) -> Result<()> {
+        if version.owner != self.id {
+            return Err(anyhow!(VirtMemError::UnknownVersion(version.idx)));
+        }
+
+        let snapshot = self
+            .versions
+            .get(version.idx)
+            .ok_or_else(|| anyhow!(VirtMemError::UnknownVersion(version.idx)))?;
+        self.index = snapshot.clone();
+        self.dirty.clear();
+
+        Ok(())
+    }
+}
+
+impl X64VirtualAddressSpace for MmapGpaManager {
+    fn read_gpa(&self, gpa: Gpa, buf: &mut [u8]) -> Result<()> {
+        if gpa + (buf.len() as Gpa) > (gpa & !0xfff) + 0x1000 {
+            return Err(anyhow!(VirtMemError::SpanningPage));
+        }
+
+        let (base, off) = page_off(gpa);
+        let offset = *self
+            .index
+            .get(&base)
+            .ok_or_else(|| anyhow!(VirtMemError::MissingPage(base)))?;
+
+        buf.copy_from_slice(&self.mmap[offset + off..offset + off + buf.len()]);
+
+        Ok(())
+    }
+
+    fn write_gpa(&mut self, gpa: Gpa, data: &[u8]) -> Result<()> {
+        if gpa + (data.len() as Gpa) > (gpa & !0xfff) + 0x1000 {
+            return Err(anyhow!(VirtMemError::SpanningPage));
+        }
+
+        let (base, off) = page_off(gpa);
+
+        // Already exclusively owned by this lineage since the last
+        // commit/fork: overwrite the existing frame instead of appending.
+        if self.dirty.contains(&base) {
+            let offset = *self.index.get(&base).expect("dirty page is indexed");
+            self.mmap[offset + off..offset + off + data.len()].copy_from_slice(data);
+            return Ok(());
+        }
+
+        let mut page = [0u8; 4096];
+
+        if let Some(&offset) = self.index.get(&base) {
+            page.copy_from_slice(&self.mmap[offset..offset + 4096]);
+        }
+
+        page[off..off + data.len()].copy_from_slice(data);
+        self.add_page(base, &page)?;
+        self.dirty.insert(base);
+
+        Ok(())
+    }
+}
+
+#[test]
+fn test_mmap_gpa_manager_open_version_is_isolated_from_later_writes() {
+    let mut path = std::env::temp_dir();
+    path.push(format!(
+        "whvp-mmap-gpa-manager-test-{}.bin",
+        std::process::id()
+    ));
+
+    let mut mgr = MmapGpaManager::create(&path).unwrap();
+    mgr.add_page(0x2000, &[0x11; 4096]).unwrap();
+
+    let v0 = mgr.commit();
+
+    mgr.write_gpa(0x2000, &[0x22; 4]).unwrap();
+
+    let mut buf = [0; 4];
+    mgr.read_gpa(0x2000, &mut buf).unwrap();
+    assert_eq!(buf, [0x22; 4]);
+
+    mgr.open_version(v0).unwrap();
+
+    let mut buf = [0; 4];
+    mgr.read_gpa(0x2000, &mut buf).unwrap();
+    assert_eq!(buf, [0x11; 4]);
+
+    drop(mgr);
+    std::fs::remove_file(&path).ok();
+}
+
+/// State of a cache slot as seen by a lookup: either unoccupied, occupied but
+/// stale (the validator says so), or occupied and safe to read straight from.
+/// Carries no reference to the slot itself — callers copy bytes in or out via
+/// `PageCache::copy_from_slot`/`copy_into_slot` instead, so a stale lookup
+/// can never be paired with a `&mut` into memory another lookup also holds.
+pub enum PageValidity {
+    Invalid,
+    ToBeValidated,
+    Valid,
+}
+
+/// Decides whether a cached page is still good to serve, without the cache
+/// itself knowing why (generation counters, TSC deadlines, dirty bitmaps...).
+pub trait CacheValidator {
+    /// Number of slots this validator tracks. `PageCache::new` asserts this
+    /// matches its own slot count, so `is_valid`/`validate_slot` can never be
+    /// called with an index the validator wasn't sized for.
+    fn slots(&self) -> usize;
+
+    fn is_valid(&self, slot: usize) -> bool;
+
+    fn validate_slot(&mut self, slot: usize);
+
+    fn invalidate_all(&mut self);
+}
+
+/// Validator that stamps every slot with a generation number and bumps a
+/// global counter on `invalidate_all`, so a full flush is O(1) regardless of
+/// how many slots are occupied (used after the guest VM resumes and may have
+/// mutated pages behind our back).
+pub struct CountValidator {
+    slot_gen: Vec<u64>,
+    gen: u64,
+}
+
+impl CountValidator {
+    pub fn new(slots: usize) -> Self {
+        CountValidator {
+            slot_gen: vec![0; slots],
+            gen: 1,
+        }
+    }
+}
+
+impl CacheValidator for CountValidator {
+    fn slots(&self) -> usize {
+        self.slot_gen.len()
+    }
+
+    fn is_valid(&self, slot: usize) -> bool {
+        self.slot_gen[slot] == self.gen
+    }
+
+    fn validate_slot(&mut self, slot: usize) {
+        self.slot_gen[slot] = self.gen;
+    }
+
+    fn invalidate_all(&mut self) {
+        self.gen = self.gen.wrapping_add(1);
+    }
+}
+
+/// Bounded page cache in front of any `X64VirtualAddressSpace`, modeled on
+/// memflow's cache: a fixed arena of `slots` page-sized frames is allocated
+/// once up front, so replaying long traces no longer grows memory without
+/// limit the way a raw `GpaManager::pages` map does. A page's slot is picked
+/// by `(gpa >> 12) % slots`; a colliding page simply evicts whatever was
+/// there before.
+pub struct PageCache<T, V> {
+    backing: T,
+    validator: RefCell<V>,
+    arena: *mut u8,
+    _alloc: Allocator,
+    tags: RefCell<Vec<Gpa>>,
+    slots: usize,
+}
+
+impl<T, V> PageCache<T, V>
+where
+    T: X64VirtualAddressSpace,
+    V: CacheValidator,
+{
+    pub fn new(backing: T, validator: V, slots: usize) -> Self {
+        assert!(slots > 0, "PageCache requires at least one slot");
+        assert_eq!(
+            validator.slots(),
+            slots,
+            "validator must track exactly as many slots as the cache has"
+        );
+
+        let mut alloc = Allocator::new();
+        let arena = alloc.allocate_physical_memory(slots * 4096) as *mut u8;
+
+        PageCache {
+            backing,
+            validator: RefCell::new(validator),
+            arena,
+            _alloc: alloc,
+            tags: RefCell::new(vec![u64::MAX; slots]),
+            slots,
+        }
+    }
+
+    fn slot_of(&self, base: Gpa) -> usize {
+        ((base >> 12) % self.slots as u64) as usize
+    }
+
+    /// Copies `buf.len()` bytes out of slot `idx` at byte offset `off`.
+    ///
+    /// Safety: `idx` is always produced by `slot_of`, so `idx * 4096` is in
+    /// bounds of the `slots * 4096`-byte arena allocated in `new`; callers
+    /// (via the `SpanningPage` check in `read_gpa`) guarantee `off + buf.len()
+    /// <= 4096`. Going through `ptr::copy_nonoverlapping` instead of handing
+    /// back a `&mut [u8]` means no Rust reference into the arena ever
+    /// outlives this call, so there is nothing for a second call on the same
+    /// `&self` to alias.
+    fn copy_from_slot(&self, idx: usize, off: usize, buf: &mut [u8]) {
+        unsafe {
+            std::ptr::copy_nonoverlapping(self.arena.add(idx * 4096 + off), buf.as_mut_ptr(), buf.len());
+        }
+    }
+
+    /// Copies `data` into slot `idx` at byte offset `off`. Same safety
+    /// argument as `copy_from_slot`, mirrored for the write direction.
+    fn copy_into_slot(&self, idx: usize, off: usize, data: &[u8]) {
+        unsafe {
+            std::ptr::copy_nonoverlapping(data.as_ptr(), self.arena.add(idx * 4096 + off), data.len());
+        }
+    }
+
+    fn validity(&self, base: Gpa) -> PageValidity {
+        let idx = self.slot_of(base);
+
+        if self.tags.borrow()[idx] != base {
+            return PageValidity::Invalid;
+        }
+
+        if !self.validator.borrow().is_valid(idx) {
+            return PageValidity::ToBeValidated;
+        }
+
+        PageValidity::Valid
+    }
+
+    fn fill_slot(&self, base: Gpa) -> Result<()> {
+        let idx = self.slot_of(base);
+
+        let mut page = [0u8; 4096];
+        self.backing.read_gpa(base, &mut page)?;
+        self.copy_into_slot(idx, 0, &page);
+
+        self.tags.borrow_mut()[idx] = base;
+        self.validator.borrow_mut().validate_slot(idx);
+
+        Ok(())
+    }
+
+    /// Drop the whole cache in O(1): called after the guest VM resumes,
+    /// since execution may have mutated any page behind our back.
+    pub fn invalidate_all(&mut self) {
+        self.validator.get_mut().invalidate_all();
+    }
+}
+
+impl<T, V> X64VirtualAddressSpace for PageCache<T, V>
+where
+    T: X64VirtualAddressSpace,
+    V: CacheValidator,
+{
+    fn read_gpa(&self, gpa: Gpa, buf: &mut [u8]) -> Result<()> {
+        if gpa + (buf.len() as Gpa) > (gpa & !0xfff) + 0x1000 {
+            return Err(anyhow!(VirtMemError::SpanningPage));
+        }
+
+        let (base, off) = page_off(gpa);
+
+        if matches!(self.validity(base), PageValidity::Invalid | PageValidity::ToBeValidated) {
+            self.fill_slot(base)?;
+        }
+
+        match self.validity(base) {
+            PageValidity::Valid => {
+                self.copy_from_slot(self.slot_of(base), off, buf);
+                Ok(())
+            }
+            _ => Err(anyhow!(VirtMemError::MissingPage(base))),
+        }
+    }
+
+    fn write_gpa(&mut self, gpa: Gpa, data: &[u8]) -> Result<()> {
+        if gpa + (data.len() as Gpa) > (gpa & !0xfff) + 0x1000 {
+            return Err(anyhow!(VirtMemError::SpanningPage));
+        }
+
+        self.backing.write_gpa(gpa, data)?;
+
+        let (base, off) = page_off(gpa);
+        let idx = self.slot_of(base);
+        if self.tags.borrow()[idx] == base {
+            self.copy_into_slot(idx, off, data);
+        }
+
+        Ok(())
+    }
+}
+
+/// Granularity of a resolved translation, needed to recompute the right
+/// offset mask on a TLB hit (1 GiB / 2 MiB / 4 KiB pages each keep a
+/// different number of low bits of the `gva` untranslated).
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum PageSize {
+    Size4Kb,
+    Size2Mb,
+    Size1Gb,
+}
+
+impl PageSize {
+    const fn mask(self) -> u64 {
+        match self {
+            PageSize::Size4Kb => 0xfff,
+            PageSize::Size2Mb => 0x1f_ffff,
+            PageSize::Size1Gb => 0x3fff_ffff,
+        }
+    }
+}
+
+/// Software TLB in front of any `X64VirtualAddressSpace`, caching the result
+/// of `translate_gva` keyed on `(cr3, gva & !0xfff)` so a 4 KB-spanning
+/// `read_gva`/`write_gva` doesn't re-walk PML4/PDPT/PD/PT on every chunk.
+/// Entries must be dropped with `flush_tlb*` whenever a caller mutates page
+/// tables or switches address spaces, the same way a real CPU's TLB is
+/// invalidated on `invlpg`/`mov cr3`.
+pub struct TlbCache<T> {
+    backing: T,
+    tlb: RefCell<FastMap64<(Gpa, Gva), (Gpa, PageSize)>>,
+}
+
+impl<T> TlbCache<T>
+where
+    T: X64VirtualAddressSpace,
+{
+    pub fn new(backing: T) -> Self {
+        TlbCache {
+            backing,
+            tlb: RefCell::new(FastMap64::default()),
+        }
+    }
+
+    pub fn flush_tlb(&self) {
+        self.tlb.borrow_mut().clear();
+    }
+
+    pub fn flush_tlb_cr3(&self, cr3: Gpa) {
+        self.tlb.borrow_mut().retain(|&(c, _), _| c != cr3);
+    }
+
+    pub fn flush_tlb_gva(&self, gva: Gva) {
+        // Entries are keyed on the 4 KiB-aligned `gva`, but a huge-page hit
+        // caches that same base/size under every 4 KiB key it spans. Masking
+        // both the stored key and `gva` down to the entry's own page size
+        // recovers the huge-aligned base, so a single `invlpg`-style flush
+        // drops the whole huge mapping rather than just the one 4 KiB slot
+        // that happened to be looked up.
+        self.tlb
+            .borrow_mut()
+            .retain(|&(_, key), &mut (_, size)| key & !size.mask() != gva & !size.mask());
+    }
+}
+
+impl<T> X64VirtualAddressSpace for TlbCache<T>
+where
+    T: X64VirtualAddressSpace,
+{
+    fn read_gpa(&self, gpa: Gpa, buf: &mut [u8]) -> Result<()> {
+        self.backing.read_gpa(gpa, buf)
+    }
+
+    fn write_gpa(&mut self, gpa: Gpa, data: &[u8]) -> Result<()> {
+        self.backing.write_gpa(gpa, data)
+    }
+
+    fn paging_mode(&self) -> PagingMode {
+        self.backing.paging_mode()
+    }
+
+    fn translate_gva(&self, cr3: Gpa, gva: Gva) -> Result<Gpa> {
+        let page_key = gva & !0xfff;
+
+        if let Some(&(base, size)) = self.tlb.borrow().get(&(cr3, page_key)) {
+            return Ok(base + (gva & size.mask()));
+        }
+
+        let (mut pml4_base, _) = base_flags(cr3);
+
+        if self.paging_mode() == PagingMode::Long5Level {
+            let pml5e_addr = pml4_base + pml5_index(gva) * 8;
+            let pml5e = self.backing.read_gpa_u64(pml5e_addr)?;
+
+            let (next_base, pml5e_flags) = base_flags(pml5e);
+
+            if pml5e_flags & 1 == 0 {
+                return Err(anyhow!(VirtMemError::Pml5eNotPresent));
+            }
+
+            pml4_base = next_base;
+        }
+
+        let pml4e_addr = pml4_base + pml4_index(gva) * 8;
+        let pml4e = self.backing.read_gpa_u64(pml4e_addr)?;
+
+        let (pdpt_base, pml4e_flags) = base_flags(pml4e);
+
+        if pml4e_flags & 1 == 0 {
+            return Err(anyhow!(VirtMemError::Pml4eNotPresent));
+        }
+
+        let pdpte_addr = pdpt_base + pdpt_index(gva) * 8;
+        let pdpte = self.backing.read_gpa_u64(pdpte_addr)?;
+
+        let (pd_base, pdpte_flags) = base_flags(pdpte);
+
+        if pdpte_flags & 1 == 0 {
+            return Err(anyhow!(VirtMemError::PdpteNotPresent));
+        }
+
+        if pdpte_flags & (1 << 7) != 0 {
+            self.tlb
+                .borrow_mut()
+                .insert((cr3, page_key), (pd_base, PageSize::Size1Gb));
+            return Ok(pd_base + (gva & PageSize::Size1Gb.mask()));
+        }
+
+        let pde_addr = pd_base + pd_index(gva) * 8;
+        let pde = self.backing.read_gpa_u64(pde_addr)?;
+
+        let (pt_base, pde_flags) = base_flags(pde);
+
+        if pde_flags & 1 == 0 {
+            return Err(anyhow!(VirtMemError::PdeNotPresent));
+        }
+
+        if pde_flags & (1 << 7) != 0 {
+            self.tlb
+                .borrow_mut()
+                .insert((cr3, page_key), (pt_base, PageSize::Size2Mb));
+            return Ok(pt_base + (gva & PageSize::Size2Mb.mask()));
+        }
+
+        let pte_addr = pt_base + pt_index(gva) * 8;
+        let pte = self.backing.read_gpa_u64(pte_addr)?;
+
+        let (pte_paddr, pte_flags) = pte_flags(pte);
+
+        if pte_flags & 1 == 0 {
+            return Err(anyhow!(VirtMemError::PteNotPresent));
+        }
+
+        self.tlb
+            .borrow_mut()
+            .insert((cr3, page_key), (pte_paddr, PageSize::Size4Kb));
+        Ok(pte_paddr + page_offset(gva))
+    }
+}
+
+#[test]
+fn test_flush_tlb_gva_drops_whole_huge_page() {
+    const PRESENT: u64 = 1;
+    const WRITABLE: u64 = 1 << 1;
+    const HUGE: u64 = 1 << 7;
+
+    let mut gm = GpaManager::new();
+
+    // gva = 0 walks index 0 at PML4/PDPT; PDPTE is a 1 GiB huge page so the
+    // walk stops there. A second 4 KiB page within the same GiB (gva =
+    // 0x1000) resolves to the same huge mapping under a different TLB key.
+    gm.add_page(0x1000, [0; 4096]);
+    gm.add_page(0x2000, [0; 4096]);
+
+    gm.write_gpa(0x1000, &(0x2000u64 | PRESENT | WRITABLE).to_le_bytes())
+        .unwrap();
+    gm.write_gpa(0x2000, &(0x3000_0000u64 | PRESENT | WRITABLE | HUGE).to_le_bytes())
+        .unwrap();
+
+    let tlb = TlbCache::new(gm);
+
+    assert_eq!(tlb.translate_gva(0x1000, 0x0).unwrap(), 0x3000_0000);
+    assert_eq!(tlb.translate_gva(0x1000, 0x1000).unwrap(), 0x3000_1000);
+
+    // Both lookups cached the same huge page under different 4 KiB keys.
+    // Flushing just the first address must still evict the second.
+    tlb.flush_tlb_gva(0x0);
+
+    assert!(!tlb.tlb.borrow().contains_key(&(0x1000, 0x0)));
+    assert!(!tlb.tlb.borrow().contains_key(&(0x1000, 0x1000)));
+}
+
 #[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
 pub enum VirtMemError {
+    Pml5eNotPresent,
     Pml4eNotPresent,
     PdpteNotPresent,
     PdeNotPresent,
     PteNotPresent,
     SpanningPage,
     MissingPage(u64),
+    WriteProtected,
+    UnknownVersion(usize),
 }
 
 impl fmt::Display for VirtMemError {